@@ -0,0 +1,134 @@
+use std::prelude::v1::*;
+
+use std::collections::BTreeMap;
+
+/// A stack of overlay deltas that gives a `StateDB` cheap, nested unwinds
+/// without recomputing a trie root for every sub-call frame or reverted
+/// transaction.
+///
+/// Each open checkpoint records, for every key dirtied since it was opened, the
+/// value that key held *before* the change (`None` for a key that was absent).
+/// Reverting replays those prior values for exactly the touched keys;
+/// discarding merges the inner frame's dirty set into the enclosing one so a
+/// later revert of the parent still unwinds them.
+///
+/// This is the building block behind the [`checkpoint`](crate::StateDB::checkpoint)
+/// family on [`StateDB`](crate::StateDB): an implementation routes each dirtying
+/// write through [`record`](Self::record) and writes back the map returned by
+/// [`revert_to_checkpoint`](Self::revert_to_checkpoint). The `staging`/`commit`
+/// flow stays the final flush point.
+#[derive(Debug, Clone)]
+pub struct CheckpointStack<K, V> {
+    frames: Vec<BTreeMap<K, Option<V>>>,
+}
+
+impl<K: Ord + Clone, V: Clone> CheckpointStack<K, V> {
+    pub fn new() -> Self {
+        Self { frames: Vec::new() }
+    }
+
+    /// Returns the number of open checkpoints.
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Opens a checkpoint over the current overlay and returns its id.
+    pub fn checkpoint(&mut self) -> usize {
+        self.frames.push(BTreeMap::new());
+        self.frames.len() - 1
+    }
+
+    /// Records the value `key` held before it is dirtied under the innermost
+    /// open checkpoint. The first record for a key wins, so the captured value
+    /// is the one from when the checkpoint was opened. A no-op when no
+    /// checkpoint is open.
+    pub fn record(&mut self, key: K, prior: Option<V>) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.entry(key).or_insert(prior);
+        }
+    }
+
+    /// Drops checkpoint `id` and everything opened after it, returning the
+    /// prior values the caller must write back to undo those frames. For a key
+    /// touched in several reverted frames the earliest captured value wins.
+    pub fn revert_to_checkpoint(&mut self, id: usize) -> BTreeMap<K, Option<V>> {
+        let mut restore = BTreeMap::new();
+        while self.frames.len() > id {
+            // Pop innermost first and let outer (earlier-opened) frames
+            // overwrite, so the value from when `id` opened is what remains.
+            for (key, prior) in self.frames.pop().unwrap() {
+                restore.insert(key, prior);
+            }
+        }
+        restore
+    }
+
+    /// Accepts checkpoint `id` and everything opened after it, merging their
+    /// dirty sets into the enclosing checkpoint so a later revert of the parent
+    /// still unwinds them. When `id` is the outermost checkpoint the changes
+    /// become permanent.
+    pub fn discard_checkpoint(&mut self, id: usize) {
+        let mut merged = BTreeMap::new();
+        while self.frames.len() > id {
+            for (key, prior) in self.frames.pop().unwrap() {
+                merged.insert(key, prior);
+            }
+        }
+        if let Some(parent) = self.frames.last_mut() {
+            for (key, prior) in merged {
+                parent.entry(key).or_insert(prior);
+            }
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Default for CheckpointStack<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revert_replays_prior_values_for_touched_keys() {
+        let mut stack = CheckpointStack::<u64, u64>::new();
+        let id = stack.checkpoint();
+        // key 1 existed as 10 before the checkpoint, key 2 was absent.
+        stack.record(1, Some(10));
+        stack.record(2, None);
+        let restore = stack.revert_to_checkpoint(id);
+        assert_eq!(restore.get(&1), Some(&Some(10)));
+        assert_eq!(restore.get(&2), Some(&None));
+        assert_eq!(stack.depth(), 0);
+    }
+
+    #[test]
+    fn nested_revert_keeps_earliest_prior() {
+        let mut stack = CheckpointStack::<u64, u64>::new();
+        let outer = stack.checkpoint();
+        stack.record(1, Some(1));
+        stack.checkpoint();
+        // inner records a later prior for the same key; it must not win.
+        stack.record(1, Some(2));
+        let restore = stack.revert_to_checkpoint(outer);
+        assert_eq!(restore.get(&1), Some(&Some(1)));
+    }
+
+    #[test]
+    fn discard_merges_child_dirty_set_into_parent() {
+        let mut stack = CheckpointStack::<u64, u64>::new();
+        let outer = stack.checkpoint();
+        stack.record(1, Some(1));
+        let inner = stack.checkpoint();
+        stack.record(2, None);
+        stack.discard_checkpoint(inner);
+        assert_eq!(stack.depth(), 1);
+        // Reverting the parent now also unwinds the child's key 2.
+        let restore = stack.revert_to_checkpoint(outer);
+        assert_eq!(restore.get(&1), Some(&Some(1)));
+        assert_eq!(restore.get(&2), Some(&None));
+    }
+}
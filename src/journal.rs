@@ -0,0 +1,285 @@
+use core::marker::PhantomData;
+use std::prelude::v1::*;
+
+use eth_types::{HexBytes, SH256};
+
+use std::borrow::Cow;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use super::{Error, Hasher, NodeDB};
+
+/// Controls how much history a [`JournalStore`] keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalMode {
+    /// Reference-count nodes and prune eras older than `history_depth`.
+    Pruned { history_depth: u32 },
+    /// Never prune; retain every node for full historical access.
+    Archive,
+}
+
+/// A node together with the number of live eras that reference it.
+#[derive(Debug, Clone)]
+struct RefNode<T> {
+    data: Arc<T>,
+    rc: i64,
+}
+
+/// The set of node changes produced by a single `commit`, keyed by block root.
+#[derive(Debug, Clone)]
+struct Era {
+    root: SH256,
+    inserts: Vec<SH256>,
+    removes: Vec<SH256>,
+}
+
+#[derive(Debug)]
+struct Journal<T> {
+    nodes: BTreeMap<SH256, RefNode<T>>,
+    codes: BTreeMap<SH256, Arc<HexBytes>>,
+    eras: VecDeque<Era>,
+    pruned: u64,
+}
+
+/// A persistent [`NodeDB`] backend that reference-counts nodes across block
+/// commits and prunes unreferenced history after a configurable depth, so a
+/// long-running node does not grow without bound.
+///
+/// It is modelled on era-based journaldb: every
+/// [`commit_era`](Self::commit_era) forms an era keyed by the given block root
+/// that records the nodes it inserted and the decrements it left pending
+/// (`NodeDB::commit` keys its era by the zero hash). A staged node's refcount
+/// is bumped as soon as its era is committed; a removal is only
+/// applied when its era ages past `history_depth`, at which point any node
+/// whose count reaches zero is physically deleted. An abandoned fork is undone
+/// with [`rollback_era`](Self::rollback_era). [`JournalMode::Archive`] skips
+/// pruning entirely.
+#[derive(Debug, Clone)]
+pub struct JournalStore<T, H: Hasher<T>> {
+    inner: Arc<Mutex<Journal<T>>>,
+    mode: JournalMode,
+    staging: BTreeMap<SH256, Arc<T>>,
+    removes: Vec<SH256>,
+    _phantom: PhantomData<H>,
+}
+
+impl<T, H: Hasher<T>> JournalStore<T, H> {
+    /// Creates a store that prunes eras older than `history_depth`.
+    pub fn new(history_depth: u32) -> Self {
+        Self::with_mode(JournalMode::Pruned { history_depth })
+    }
+
+    /// Creates an archive store that never prunes.
+    pub fn archive() -> Self {
+        Self::with_mode(JournalMode::Archive)
+    }
+
+    pub fn with_mode(mode: JournalMode) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Journal {
+                nodes: BTreeMap::new(),
+                codes: BTreeMap::new(),
+                eras: VecDeque::new(),
+                pruned: 0,
+            })),
+            mode,
+            staging: BTreeMap::new(),
+            removes: Vec::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Flushes the staged nodes as a new era keyed by `root` and prunes any era
+    /// that has now aged past `history_depth`, returning the number of nodes
+    /// committed.
+    pub fn commit_era(&mut self, root: SH256) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let mut inserts = Vec::with_capacity(self.staging.len());
+        for (hash, node) in core::mem::take(&mut self.staging) {
+            match inner.nodes.entry(hash) {
+                Entry::Occupied(mut e) => e.get_mut().rc += 1,
+                Entry::Vacant(e) => {
+                    e.insert(RefNode { data: node, rc: 1 });
+                }
+            }
+            inserts.push(hash);
+        }
+        let commit_len = inserts.len();
+        let removes = core::mem::take(&mut self.removes);
+        inner.eras.push_back(Era {
+            root,
+            inserts,
+            removes,
+        });
+        if let JournalMode::Pruned { history_depth } = self.mode {
+            while inner.eras.len() as u32 > history_depth {
+                let era = inner.eras.pop_front().unwrap();
+                for hash in era.removes {
+                    decrement(&mut inner, &hash);
+                }
+            }
+        }
+        commit_len
+    }
+
+    /// Undoes an abandoned fork's era, rolling back the refcount bumps from its
+    /// inserts and dropping its pending removes.
+    pub fn rollback_era(&mut self, root: &SH256) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(pos) = inner.eras.iter().position(|era| &era.root == root) {
+            let era = inner.eras.remove(pos).unwrap();
+            for hash in era.inserts {
+                decrement(&mut inner, &hash);
+            }
+        }
+    }
+
+    /// Returns the number of distinct nodes currently held.
+    pub fn live_node_count(&self) -> usize {
+        self.inner.lock().unwrap().nodes.len()
+    }
+
+    /// Returns the number of nodes physically deleted so far, by pruning or by
+    /// [`rollback_era`](Self::rollback_era).
+    pub fn pruned_node_count(&self) -> u64 {
+        self.inner.lock().unwrap().pruned
+    }
+}
+
+/// Decrements a node's refcount, deleting it and bumping the pruned counter
+/// when the count reaches zero.
+fn decrement<T>(inner: &mut Journal<T>, hash: &SH256) {
+    if let Entry::Occupied(mut e) = inner.nodes.entry(*hash) {
+        e.get_mut().rc -= 1;
+        if e.get().rc <= 0 {
+            e.remove();
+            inner.pruned += 1;
+        }
+    }
+}
+
+impl<T, H: Hasher<T>> NodeDB for JournalStore<T, H> {
+    type Node = T;
+
+    fn fork(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            mode: self.mode,
+            staging: BTreeMap::new(),
+            removes: Vec::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    fn get(&self, index: &SH256) -> Result<Option<Arc<Self::Node>>, Error> {
+        if let Some(node) = self.staging.get(index) {
+            return Ok(Some(node.clone()));
+        }
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.nodes.get(index).map(|n| n.data.clone()))
+    }
+
+    fn add_node(&mut self, node: &Arc<Self::Node>) {
+        match self.staging.entry(H::hash(&node)) {
+            Entry::Occupied(_) => {}
+            Entry::Vacant(entry) => {
+                entry.insert(node.clone());
+            }
+        }
+    }
+
+    fn set_code(&mut self, hash: SH256, code: Cow<HexBytes>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.codes.insert(hash, Arc::new(code.into_owned()));
+    }
+
+    fn get_code(&mut self, hash: &SH256) -> Result<Option<Arc<HexBytes>>, Error> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.codes.get(hash).cloned())
+    }
+
+    fn remove_staging_node(&mut self, node: &Arc<Self::Node>) {
+        let hash = H::hash(&node);
+        if self.staging.remove(&hash).is_none() {
+            // A committed node: record a pending decrement for the next era.
+            self.removes.push(hash);
+        }
+    }
+
+    fn staging(&mut self, node: Self::Node) -> Arc<Self::Node> {
+        let node = Arc::new(node);
+        self.add_node(&node);
+        node
+    }
+
+    fn commit(&mut self) -> usize {
+        self.commit_era(SH256::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[derive(Debug)]
+    struct TestNode(u8);
+
+    struct TestHasher;
+    impl Hasher<TestNode> for TestHasher {
+        fn hash(n: &TestNode) -> SH256 {
+            sh(n.0)
+        }
+    }
+
+    fn sh(b: u8) -> SH256 {
+        let mut h = SH256::default();
+        h.as_bytes_mut()[0] = b;
+        h
+    }
+
+    #[test]
+    fn prune_drops_node_once_refcount_hits_zero() {
+        let mut db = JournalStore::<TestNode, TestHasher>::new(1);
+        let node = Arc::new(TestNode(1));
+        db.add_node(&node);
+        db.commit_era(sh(10)); // era keeps the node alive (rc = 1)
+        assert_eq!(db.live_node_count(), 1);
+
+        db.remove_staging_node(&node); // pending decrement for the next era
+        db.commit_era(sh(11)); // ages out the insert era, not the remove era
+        assert_eq!(db.live_node_count(), 1);
+
+        db.commit_era(sh(12)); // ages out the remove era -> rc 0 -> deleted
+        assert_eq!(db.live_node_count(), 0);
+        assert_eq!(db.pruned_node_count(), 1);
+    }
+
+    #[test]
+    fn rollback_undoes_inserts() {
+        let mut db = JournalStore::<TestNode, TestHasher>::new(100);
+        let node = Arc::new(TestNode(1));
+        db.add_node(&node);
+        db.commit_era(sh(42));
+        assert_eq!(db.live_node_count(), 1);
+
+        db.rollback_era(&sh(42));
+        assert_eq!(db.live_node_count(), 0);
+        assert!(db.get(&sh(1)).unwrap().is_none());
+    }
+
+    #[test]
+    fn archive_never_prunes() {
+        let mut db = JournalStore::<TestNode, TestHasher>::archive();
+        let node = Arc::new(TestNode(1));
+        db.add_node(&node);
+        db.commit_era(sh(1));
+        db.remove_staging_node(&node);
+        for i in 2..10 {
+            db.commit_era(sh(i));
+        }
+        assert_eq!(db.live_node_count(), 1);
+        assert_eq!(db.pruned_node_count(), 0);
+    }
+}
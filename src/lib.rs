@@ -9,5 +9,14 @@ pub use cache::*;
 mod types;
 pub use types::*;
 
+mod checkpoint;
+pub use checkpoint::*;
+
 mod mem_store;
-pub use mem_store::*;
\ No newline at end of file
+pub use mem_store::*;
+
+mod recording;
+pub use recording::*;
+
+mod journal;
+pub use journal::*;
\ No newline at end of file
@@ -9,7 +9,7 @@ use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
 
-use super::NodeDB;
+use super::{Error, NodeDB, NodeWeight};
 
 pub type MemStore<T, H> = LruMemStore<T, H>;
 
@@ -17,11 +17,58 @@ pub trait Hasher<T> {
     fn hash(n: &T) -> SH256;
 }
 
+/// Selects how a store keys storage-trie nodes in the shared `kv` map.
+///
+/// `Plain` passes node hashes through unchanged, which is what global tries
+/// (and read-only proof import) want. `Mangled` folds the owning account's
+/// address hash into every key so two accounts whose storage tries contain
+/// identically-shaped subtrees do not alias the same `SH256` slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeDbLayout {
+    Mangled,
+    Plain,
+}
+
+/// Derives a per-account storage key by keccak-hashing the owning account's
+/// address hash XOR-ed with the node hash, so the two inputs are folded into a
+/// fresh `SH256` that cannot alias the plain node hash of another account.
+fn mangle(scope: &SH256, hash: &SH256) -> SH256 {
+    let mut folded = [0u8; 32];
+    for ((out, s), h) in folded
+        .iter_mut()
+        .zip(scope.as_bytes())
+        .zip(hash.as_bytes())
+    {
+        *out = s ^ h;
+    }
+    eth_types::keccak256(&folded).into()
+}
+
+/// Selects how a [`LruMemStore`]'s caches are bounded.
+#[derive(Debug, Clone, Copy)]
+enum CacheBound {
+    /// Bound each cache by entry count (handled by `LruMap` itself).
+    Count,
+    /// Bound the code and node caches by approximate byte footprint.
+    Bytes { code: usize, node: usize },
+}
+
+/// Per-`Arc`/key overhead added to a value's payload when accounting bytes.
+const ENTRY_OVERHEAD: usize = core::mem::size_of::<SH256>() + core::mem::size_of::<usize>() * 2;
+
 #[derive(Debug, Clone)]
 pub struct LruMemStore<T, H: Hasher<T>> {
     codes: Arc<Mutex<LruMap<SH256, Arc<HexBytes>>>>,
     kv: Arc<Mutex<LruMap<SH256, Arc<T>>>>,
     staging: BTreeMap<SH256, Arc<T>>,
+    layout: NodeDbLayout,
+    scope: Option<SH256>,
+    bound: CacheBound,
+    // Measures a node's byte footprint for byte-bounded mode; `None` in
+    // count mode so count-based users never need `T: NodeWeight`.
+    weigh: Option<fn(&T) -> usize>,
+    code_bytes: Arc<Mutex<usize>>,
+    node_bytes: Arc<Mutex<usize>>,
     _phantom: PhantomData<H>,
 }
 
@@ -31,13 +78,53 @@ impl<T, H: Hasher<T>> LruMemStore<T, H> {
             codes: Arc::new(Mutex::new(LruMap::new(limit))),
             kv: Arc::new(Mutex::new(LruMap::new(limit))),
             staging: BTreeMap::new(),
+            layout: NodeDbLayout::Plain,
+            scope: None,
+            bound: CacheBound::Count,
+            weigh: None,
+            code_bytes: Arc::new(Mutex::new(0)),
+            node_bytes: Arc::new(Mutex::new(0)),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Sets the key layout used for node storage, returning the store for
+    /// chaining. `Plain` (the default) keeps the shared global-trie behavior.
+    pub fn with_layout(mut self, layout: NodeDbLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Returns a view over the same backing store scoped to `address_hash`. In
+    /// `Mangled` mode every node key is namespaced by the account, so many
+    /// accounts' storage tries can share one database safely; in `Plain` mode
+    /// the hashes pass through unchanged.
+    pub fn scoped(&self, address_hash: &SH256) -> Self {
+        Self {
+            codes: self.codes.clone(),
+            kv: self.kv.clone(),
+            staging: BTreeMap::new(),
+            layout: self.layout,
+            scope: Some(*address_hash),
+            bound: self.bound,
+            weigh: self.weigh,
+            code_bytes: self.code_bytes.clone(),
+            node_bytes: self.node_bytes.clone(),
             _phantom: PhantomData,
         }
     }
 
+    fn scoped_key(&self, hash: SH256) -> SH256 {
+        match (self.layout, &self.scope) {
+            (NodeDbLayout::Mangled, Some(scope)) => mangle(scope, &hash),
+            _ => hash,
+        }
+    }
+
     pub fn clear(&self) {
         let mut kv = self.kv.lock().unwrap();
         kv.clear();
+        *self.node_bytes.lock().unwrap() = 0;
     }
 }
 
@@ -49,24 +136,31 @@ impl<T, H: Hasher<T>> NodeDB for LruMemStore<T, H> {
             codes: self.codes.clone(),
             kv: self.kv.clone(),
             staging: BTreeMap::new(),
+            layout: self.layout,
+            scope: self.scope,
+            bound: self.bound,
+            weigh: self.weigh,
+            code_bytes: self.code_bytes.clone(),
+            node_bytes: self.node_bytes.clone(),
             _phantom: PhantomData,
         }
     }
 
-    fn get(&self, index: &SH256) -> Option<Arc<Self::Node>> {
-        let result = if let Some(node) = self.staging.get(index) {
+    fn get(&self, index: &SH256) -> Result<Option<Arc<Self::Node>>, Error> {
+        let index = self.scoped_key(*index);
+        let result = if let Some(node) = self.staging.get(&index) {
             Some(node.clone())
         } else {
             let mut kv = self.kv.lock().unwrap();
-            let data = kv.get(index).cloned();
+            let data = kv.get(&index).cloned();
             data
         };
         // glog::info!("store get: {:?} -> {:?}", index, result);
-        result
+        Ok(result)
     }
 
     fn add_node(&mut self, node: &Arc<Self::Node>) {
-        match self.staging.entry(H::hash(&node)) {
+        match self.staging.entry(self.scoped_key(H::hash(&node))) {
             Entry::Occupied(_) => {}
             Entry::Vacant(entry) => {
                 entry.insert(node.clone());
@@ -75,17 +169,35 @@ impl<T, H: Hasher<T>> NodeDB for LruMemStore<T, H> {
     }
 
     fn set_code(&mut self, hash: SH256, code: Cow<HexBytes>) {
-        let mut codes = self.codes.lock().unwrap();
-        codes.insert(hash, Arc::new(code.into_owned()));
+        let code = Arc::new(code.into_owned());
+        match self.bound {
+            CacheBound::Bytes { code: limit, .. } => {
+                {
+                    let mut codes = self.codes.lock().unwrap();
+                    let mut total = self.code_bytes.lock().unwrap();
+                    if let Some(prev) = codes.get(&hash) {
+                        *total -= prev.len() + ENTRY_OVERHEAD;
+                    }
+                    *total += code.len() + ENTRY_OVERHEAD;
+                    codes.insert(hash, code);
+                }
+                self.evict_codes(limit);
+            }
+            CacheBound::Count => {
+                let mut codes = self.codes.lock().unwrap();
+                codes.insert(hash, code);
+            }
+        }
     }
 
-    fn get_code(&mut self, hash: &SH256) -> Option<Arc<HexBytes>> {
+    fn get_code(&mut self, hash: &SH256) -> Result<Option<Arc<HexBytes>>, Error> {
         let mut codes = self.codes.lock().unwrap();
-        codes.get(hash).map(|v| v.clone())
+        Ok(codes.get(hash).map(|v| v.clone()))
     }
 
     fn remove_staging_node(&mut self, node: &Arc<Self::Node>) {
-        self.staging.remove(&H::hash(&node));
+        let key = self.scoped_key(H::hash(&node));
+        self.staging.remove(&key);
     }
 
     fn staging(&mut self, node: Self::Node) -> Arc<Self::Node> {
@@ -95,19 +207,105 @@ impl<T, H: Hasher<T>> NodeDB for LruMemStore<T, H> {
     }
 
     fn commit(&mut self) -> usize {
-        let mut kv = self.kv.lock().unwrap();
         let commit_len = self.staging.len();
-        kv.append(&mut self.staging);
+        let node_limit = match self.bound {
+            CacheBound::Bytes { node, .. } => Some(node),
+            CacheBound::Count => None,
+        };
+        {
+            let mut kv = self.kv.lock().unwrap();
+            if let Some(weigh) = self.weigh {
+                // `append` overwrites entries whose hash already exists, so
+                // account for the delta: subtract any node we are replacing
+                // before adding the staged one, or `node_bytes` drifts upward
+                // as shared branch nodes are re-committed every block.
+                let mut total = self.node_bytes.lock().unwrap();
+                for (hash, node) in &self.staging {
+                    if let Some(prev) = kv.peek(hash) {
+                        *total -= weigh(prev) + ENTRY_OVERHEAD;
+                    }
+                    *total += weigh(node) + ENTRY_OVERHEAD;
+                }
+            }
+            kv.append(&mut self.staging);
+        }
+        if let Some(limit) = node_limit {
+            self.evict_nodes(limit);
+        }
         commit_len
     }
 }
 
+impl<T, H: Hasher<T>> LruMemStore<T, H> {
+    /// Evicts least-recently-used nodes until the node cache falls under `limit`
+    /// bytes. A no-op in count mode, where `weigh` is `None`.
+    fn evict_nodes(&self, limit: usize) {
+        let weigh = match self.weigh {
+            Some(weigh) => weigh,
+            None => return,
+        };
+        let mut kv = self.kv.lock().unwrap();
+        let mut total = self.node_bytes.lock().unwrap();
+        while *total > limit {
+            match kv.pop_lru() {
+                Some((_, node)) => *total -= weigh(&node) + ENTRY_OVERHEAD,
+                None => break,
+            }
+        }
+    }
+
+    /// Evicts least-recently-used contract code until the code cache falls under
+    /// `limit` bytes.
+    fn evict_codes(&self, limit: usize) {
+        let mut codes = self.codes.lock().unwrap();
+        let mut total = self.code_bytes.lock().unwrap();
+        while *total > limit {
+            match codes.pop_lru() {
+                Some((_, code)) => *total -= code.len() + ENTRY_OVERHEAD,
+                None => break,
+            }
+        }
+    }
+}
+
+impl<T: NodeWeight, H: Hasher<T>> LruMemStore<T, H> {
+    /// Creates a store bounded by approximate memory footprint instead of entry
+    /// count, with independent ceilings for the code cache and the node cache.
+    /// Each `set_code` and each `commit` evicts least-recently-used entries
+    /// until the affected cache's total bytes fall back under its ceiling. Only this constructor requires
+    /// `T: NodeWeight`; count-based [`new`](Self::new) users are unaffected.
+    pub fn with_byte_limit(code_bytes: usize, node_bytes: usize) -> Self {
+        // Cap each cache by the most entries its byte budget could ever hold —
+        // one entry is at least ENTRY_OVERHEAD bytes — so byte eviction stays
+        // the binding limit without asking LruMap to preallocate usize::MAX.
+        let code_cap = code_bytes / ENTRY_OVERHEAD + 1;
+        let node_cap = node_bytes / ENTRY_OVERHEAD + 1;
+        Self {
+            codes: Arc::new(Mutex::new(LruMap::new(code_cap))),
+            kv: Arc::new(Mutex::new(LruMap::new(node_cap))),
+            staging: BTreeMap::new(),
+            layout: NodeDbLayout::Plain,
+            scope: None,
+            bound: CacheBound::Bytes {
+                code: code_bytes,
+                node: node_bytes,
+            },
+            weigh: Some(<T as NodeWeight>::byte_len),
+            code_bytes: Arc::new(Mutex::new(0)),
+            node_bytes: Arc::new(Mutex::new(0)),
+            _phantom: PhantomData,
+        }
+    }
+}
+
 
 #[derive(Debug, Clone)]
 pub struct BTreeStore<T, H: Hasher<T>> {
     codes: Arc<Mutex<BTreeMap<SH256, Arc<HexBytes>>>>,
     kv: Arc<Mutex<BTreeMap<SH256, Arc<T>>>>,
     staging: BTreeMap<SH256, Arc<T>>,
+    layout: NodeDbLayout,
+    scope: Option<SH256>,
     _phantom: PhantomData<H>,
 }
 
@@ -117,10 +315,40 @@ impl<T, H: Hasher<T>> BTreeStore<T, H> {
             codes: Arc::new(Mutex::new(BTreeMap::new())),
             kv: Arc::new(Mutex::new(BTreeMap::new())),
             staging: BTreeMap::new(),
+            layout: NodeDbLayout::Plain,
+            scope: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Sets the key layout used for node storage, returning the store for
+    /// chaining. `Plain` (the default) keeps the shared global-trie behavior.
+    pub fn with_layout(mut self, layout: NodeDbLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Returns a view over the same backing store scoped to `address_hash`,
+    /// namespacing node keys per account in `Mangled` mode (see
+    /// [`LruMemStore::scoped`]).
+    pub fn scoped(&self, address_hash: &SH256) -> Self {
+        Self {
+            codes: self.codes.clone(),
+            kv: self.kv.clone(),
+            staging: BTreeMap::new(),
+            layout: self.layout,
+            scope: Some(*address_hash),
             _phantom: PhantomData,
         }
     }
 
+    fn scoped_key(&self, hash: SH256) -> SH256 {
+        match (self.layout, &self.scope) {
+            (NodeDbLayout::Mangled, Some(scope)) => mangle(scope, &hash),
+            _ => hash,
+        }
+    }
+
     pub fn clear(&self) {
         let mut kv = self.kv.lock().unwrap();
         kv.clear();
@@ -135,24 +363,27 @@ impl<T, H: Hasher<T>> NodeDB for BTreeStore<T, H> {
             codes: self.codes.clone(),
             kv: self.kv.clone(),
             staging: BTreeMap::new(),
+            layout: self.layout,
+            scope: self.scope,
             _phantom: PhantomData,
         }
     }
 
-    fn get(&self, index: &SH256) -> Option<Arc<Self::Node>> {
-        let result = if let Some(node) = self.staging.get(index) {
+    fn get(&self, index: &SH256) -> Result<Option<Arc<Self::Node>>, Error> {
+        let index = self.scoped_key(*index);
+        let result = if let Some(node) = self.staging.get(&index) {
             Some(node.clone())
         } else {
             let kv = self.kv.lock().unwrap();
-            let data = kv.get(index).cloned();
+            let data = kv.get(&index).cloned();
             data
         };
         // glog::info!("store get: {:?} -> {:?}", index, result);
-        result
+        Ok(result)
     }
 
     fn add_node(&mut self, node: &Arc<Self::Node>) {
-        match self.staging.entry(H::hash(&node)) {
+        match self.staging.entry(self.scoped_key(H::hash(&node))) {
             Entry::Occupied(_) => {}
             Entry::Vacant(entry) => {
                 entry.insert(node.clone());
@@ -165,13 +396,14 @@ impl<T, H: Hasher<T>> NodeDB for BTreeStore<T, H> {
         codes.insert(hash, Arc::new(code.into_owned()));
     }
 
-    fn get_code(&mut self, hash: &SH256) -> Option<Arc<HexBytes>> {
+    fn get_code(&mut self, hash: &SH256) -> Result<Option<Arc<HexBytes>>, Error> {
         let codes = self.codes.lock().unwrap();
-        codes.get(hash).map(|v| v.clone())
+        Ok(codes.get(hash).map(|v| v.clone()))
     }
 
     fn remove_staging_node(&mut self, node: &Arc<Self::Node>) {
-        self.staging.remove(&H::hash(&node));
+        let key = self.scoped_key(H::hash(&node));
+        self.staging.remove(&key);
     }
 
     fn staging(&mut self, node: Self::Node) -> Arc<Self::Node> {
@@ -186,4 +418,102 @@ impl<T, H: Hasher<T>> NodeDB for BTreeStore<T, H> {
         kv.append(&mut self.staging);
         commit_len
     }
+}
+
+#[cfg(test)]
+mod mangle_tests {
+    use super::*;
+    use eth_types::SH256;
+    use std::sync::Arc;
+
+    #[derive(Debug)]
+    struct TestNode(Vec<u8>);
+
+    struct TestHasher;
+    impl Hasher<TestNode> for TestHasher {
+        fn hash(n: &TestNode) -> SH256 {
+            sh(n.0[0])
+        }
+    }
+
+    fn sh(b: u8) -> SH256 {
+        let mut h = SH256::default();
+        h.as_bytes_mut()[0] = b;
+        h
+    }
+
+    #[test]
+    fn scoped_stores_do_not_alias_identical_node_hash() {
+        let store =
+            LruMemStore::<TestNode, TestHasher>::new(1024).with_layout(NodeDbLayout::Mangled);
+        let mut a = store.scoped(&sh(0xAA));
+        let mut b = store.scoped(&sh(0xBB));
+
+        // Same content -> same node hash (sh(7)) in both scopes.
+        let shared = Arc::new(TestNode(vec![7, 7, 7, 7]));
+        a.add_node(&shared);
+        a.commit();
+
+        assert!(a.get(&sh(7)).unwrap().is_some());
+        assert!(b.get(&sh(7)).unwrap().is_none());
+    }
+}
+
+#[cfg(test)]
+mod byte_tests {
+    use super::*;
+    use crate::NodeWeight;
+    use eth_types::SH256;
+    use std::sync::Arc;
+
+    #[derive(Debug)]
+    struct TestNode(Vec<u8>);
+
+    impl NodeWeight for TestNode {
+        fn byte_len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    struct TestHasher;
+    impl Hasher<TestNode> for TestHasher {
+        fn hash(n: &TestNode) -> SH256 {
+            let mut h = SH256::default();
+            h.as_bytes_mut()[0] = n.0[0];
+            h
+        }
+    }
+
+    fn node(id: u8, len: usize) -> Arc<TestNode> {
+        let mut data = vec![0u8; len];
+        data[0] = id;
+        Arc::new(TestNode(data))
+    }
+
+    #[test]
+    fn eviction_brings_node_bytes_under_ceiling() {
+        let per = 100;
+        let limit = 2 * (per + ENTRY_OVERHEAD) + 1; // room for ~2 nodes
+        let mut store = LruMemStore::<TestNode, TestHasher>::with_byte_limit(1 << 20, limit);
+        for id in 1..=5u8 {
+            store.add_node(&node(id, per));
+        }
+        store.commit();
+        assert!(*store.node_bytes.lock().unwrap() <= limit);
+    }
+
+    #[test]
+    fn recommitting_a_node_does_not_inflate_byte_count() {
+        let mut store = LruMemStore::<TestNode, TestHasher>::with_byte_limit(1 << 20, 1 << 20);
+        store.add_node(&node(1, 100));
+        store.commit();
+        let after_first = *store.node_bytes.lock().unwrap();
+
+        // Re-commit the same hash, as a shared branch node would be every block.
+        store.add_node(&node(1, 100));
+        store.commit();
+        let after_second = *store.node_bytes.lock().unwrap();
+
+        assert_eq!(after_first, after_second);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,241 @@
+use std::prelude::v1::*;
+
+use eth_types::SH256;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+use super::{Error, NodeDB};
+
+/// A single trie node captured while a read walks from the root to a leaf.
+///
+/// `depth` is the zero-based order in which the node was read within the
+/// current recording, i.e. the number of nodes recorded before it (a `get`
+/// that finds no node records nothing and does not advance it). For a single
+/// strictly root→leaf walk with no off-path lookups this equals the node's
+/// trie-traversal depth and orders the records root-first; see
+/// [`RecordingDB`] for the precondition.
+#[derive(Debug, Clone)]
+pub struct Record<T> {
+    pub depth: u32,
+    pub hash: SH256,
+    pub data: Arc<T>,
+}
+
+#[derive(Debug)]
+struct Recorder<T> {
+    depth: u32,
+    records: Vec<Record<T>>,
+}
+
+/// A read-only wrapper around a [`NodeDB`] that transparently records every
+/// node touched by a trie read, so callers can emit a standalone Merkle proof
+/// without a remote [`ProofFetcher`](super::ProofFetcher).
+///
+/// Every `get` is recorded, including cache and staging hits: an in-memory hit
+/// still corresponds to a node the verifier needs to reconstruct the path.
+///
+/// `depth` is counted per recorded `get` rather than derived from the caller's walk, so
+/// it equals the true traversal depth only for one strictly root→leaf read
+/// with no sibling or off-path lookups between [`new`](Self::new) and
+/// [`drain`](Self::drain). Use one recorder per proven key and `drain` it
+/// before the next read; interleaving reads mislabels `depth`.
+#[derive(Debug)]
+pub struct RecordingDB<'a, D: NodeDB> {
+    inner: &'a D,
+    recorder: Mutex<Recorder<D::Node>>,
+}
+
+impl<'a, D: NodeDB> RecordingDB<'a, D> {
+    pub fn new(inner: &'a D) -> Self {
+        Self {
+            inner,
+            recorder: Mutex::new(Recorder {
+                depth: 0,
+                records: Vec::new(),
+            }),
+        }
+    }
+
+    /// Returns the recorded nodes sorted by traversal depth and deduplicated by
+    /// hash, which is exactly the node set needed to reconstruct the path, and
+    /// resets the recorder for the next read.
+    pub fn drain(&self) -> Vec<Record<D::Node>> {
+        let mut recorder = self.recorder.lock().unwrap();
+        recorder.depth = 0;
+        let mut records = core::mem::take(&mut recorder.records);
+        records.sort_by_key(|r| r.depth);
+        let mut seen = BTreeSet::new();
+        records.retain(|r| seen.insert(r.hash));
+        records
+    }
+}
+
+impl<'a, D: NodeDB> NodeDB for RecordingDB<'a, D> {
+    type Node = D::Node;
+
+    fn fork(&self) -> Self {
+        Self::new(self.inner)
+    }
+
+    fn get(&self, index: &SH256) -> Result<Option<Arc<Self::Node>>, Error> {
+        let node = self.inner.get(index)?;
+        if let Some(node) = &node {
+            let mut recorder = self.recorder.lock().unwrap();
+            let depth = recorder.depth;
+            recorder.depth += 1;
+            recorder.records.push(Record {
+                depth,
+                hash: *index,
+                data: node.clone(),
+            });
+        }
+        Ok(node)
+    }
+
+    fn add_node(&mut self, _node: &Arc<Self::Node>) {
+        unimplemented!("RecordingDB is a read-only proof source")
+    }
+
+    /// The recorder captures trie path nodes, not contract code, and does not
+    /// serve code: this always returns `Ok(None)` without consulting the
+    /// wrapped store, rather than panicking.
+    fn get_code(&mut self, _hash: &SH256) -> Result<Option<Arc<eth_types::HexBytes>>, Error> {
+        Ok(None)
+    }
+
+    fn set_code(&mut self, _hash: SH256, _code: std::borrow::Cow<eth_types::HexBytes>) {
+        unimplemented!("RecordingDB is a read-only proof source")
+    }
+
+    fn remove_staging_node(&mut self, _node: &Arc<Self::Node>) {
+        unimplemented!("RecordingDB is a read-only proof source")
+    }
+
+    fn staging(&mut self, _node: Self::Node) -> Arc<Self::Node> {
+        unimplemented!("RecordingDB is a read-only proof source")
+    }
+
+    fn commit(&mut self) -> usize {
+        unimplemented!("RecordingDB is a read-only proof source")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Trie, TrieUpdate};
+    use std::collections::BTreeMap;
+
+    /// A minimal in-memory store: enough to drive a read through the recorder.
+    struct MapDB {
+        nodes: BTreeMap<SH256, Arc<Vec<u8>>>,
+    }
+
+    impl NodeDB for MapDB {
+        type Node = Vec<u8>;
+
+        fn fork(&self) -> Self {
+            MapDB {
+                nodes: self.nodes.clone(),
+            }
+        }
+
+        fn get(&self, index: &SH256) -> Result<Option<Arc<Vec<u8>>>, Error> {
+            Ok(self.nodes.get(index).cloned())
+        }
+
+        fn add_node(&mut self, _node: &Arc<Vec<u8>>) {}
+
+        fn get_code(&mut self, _hash: &SH256) -> Result<Option<Arc<eth_types::HexBytes>>, Error> {
+            Ok(None)
+        }
+
+        fn set_code(&mut self, _hash: SH256, _code: std::borrow::Cow<eth_types::HexBytes>) {}
+
+        fn remove_staging_node(&mut self, _node: &Arc<Vec<u8>>) {}
+
+        fn staging(&mut self, node: Vec<u8>) -> Arc<Vec<u8>> {
+            Arc::new(node)
+        }
+
+        fn commit(&mut self) -> usize {
+            0
+        }
+    }
+
+    /// A stand-in trie whose "walk" reads a fixed root→leaf chain of nodes,
+    /// exercising [`Trie::get_recorded`]'s default recorder without pulling in a
+    /// full Merkle-Patricia implementation.
+    struct ChainTrie {
+        path: Vec<SH256>,
+        value: Vec<u8>,
+    }
+
+    impl Trie for ChainTrie {
+        type DB = MapDB;
+
+        fn root_hash(&self) -> SH256 {
+            self.path.first().copied().unwrap_or_default()
+        }
+
+        fn try_get<D>(&self, db: &mut D, _key: &[u8]) -> Result<Option<Vec<u8>>, Error>
+        where
+            D: NodeDB<Node = Vec<u8>>,
+        {
+            for hash in &self.path {
+                db.get(hash)?;
+            }
+            Ok(Some(self.value.clone()))
+        }
+
+        fn get<D>(&self, db: &mut D, key: &[u8]) -> Result<Vec<u8>, Error>
+        where
+            D: NodeDB<Node = Vec<u8>>,
+        {
+            Ok(self.try_get(db, key)?.unwrap_or_default())
+        }
+
+        fn update<D>(&mut self, _db: &mut D, _updates: Vec<(&[u8], Vec<u8>)>) -> Vec<TrieUpdate>
+        where
+            D: NodeDB<Node = Vec<u8>>,
+        {
+            Vec::new()
+        }
+
+        fn new_root(&self, _new_root: SH256) -> Self {
+            ChainTrie {
+                path: self.path.clone(),
+                value: self.value.clone(),
+            }
+        }
+    }
+
+    fn sh(b: u8) -> SH256 {
+        let mut h = SH256::default();
+        h.as_bytes_mut()[0] = b;
+        h
+    }
+
+    #[test]
+    fn get_recorded_captures_the_read_path() {
+        let path = vec![sh(1), sh(2), sh(3)];
+        let mut nodes = BTreeMap::new();
+        for (i, hash) in path.iter().enumerate() {
+            nodes.insert(*hash, Arc::new(vec![i as u8]));
+        }
+        let db = MapDB { nodes };
+        let trie = ChainTrie {
+            path: path.clone(),
+            value: vec![0xaa],
+        };
+
+        let (value, records) = trie.get_recorded(&db, b"key").unwrap();
+
+        assert_eq!(value, Some(vec![0xaa]));
+        assert_eq!(records.len(), path.len());
+        for (i, record) in records.iter().enumerate() {
+            assert_eq!(record.depth, i as u32);
+            assert_eq!(record.hash, path[i]);
+        }
+    }
+}
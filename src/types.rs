@@ -13,6 +13,7 @@ pub enum Error {
     WithKey(String),
     CallRemoteFail(String),
     Flush(String),
+    Backend(String),
 }
 
 #[derive(Debug, Default)]
@@ -49,15 +50,65 @@ pub trait StateDB {
         address: &SH160,
         storages: &[SH256],
     ) -> Result<MissingState, Error>;
+
+    /// Opens a nested checkpoint over the current overlay and returns its id, to
+    /// be passed to [`revert_to_checkpoint`](Self::revert_to_checkpoint) or
+    /// [`discard_checkpoint`](Self::discard_checkpoint). Implementations back
+    /// this with a [`CheckpointStack`](crate::CheckpointStack); the default is a
+    /// no-op returning `0` for stores that do not track sub-call frames.
+    fn checkpoint(&mut self) -> usize {
+        0
+    }
+
+    /// Unwinds every write made since checkpoint `id` (and any opened after it),
+    /// restoring the overlay to the point `id` was opened. The default is a
+    /// no-op for stores that do not track sub-call frames.
+    fn revert_to_checkpoint(&mut self, _id: usize) {}
+
+    /// Accepts checkpoint `id` and everything opened after it, folding their
+    /// writes into the enclosing frame. The default is a no-op for stores that
+    /// do not track sub-call frames.
+    fn discard_checkpoint(&mut self, _id: usize) {}
 }
 
 pub trait Trie {
     type DB: NodeDB;
     fn root_hash(&self) -> SH256;
-    fn try_get(&self, db: &mut Self::DB, key: &[u8]) -> Option<Vec<u8>>;
-    fn get(&self, db: &mut Self::DB, key: &[u8]) -> Result<Vec<u8>, String>;
-    fn update(&mut self, db: &mut Self::DB, updates: Vec<(&[u8], Vec<u8>)>) -> Vec<TrieUpdate>;
+    fn try_get<D>(&self, db: &mut D, key: &[u8]) -> Result<Option<Vec<u8>>, Error>
+    where
+        D: NodeDB<Node = <Self::DB as NodeDB>::Node>;
+    fn get<D>(&self, db: &mut D, key: &[u8]) -> Result<Vec<u8>, Error>
+    where
+        D: NodeDB<Node = <Self::DB as NodeDB>::Node>;
+    fn update<D>(&mut self, db: &mut D, updates: Vec<(&[u8], Vec<u8>)>) -> Vec<TrieUpdate>
+    where
+        D: NodeDB<Node = <Self::DB as NodeDB>::Node>;
     fn new_root(&self, new_root: SH256) -> Self;
+
+    /// Reads `key` while recording every node on the path from the root to the
+    /// leaf, returning the value together with the records needed to build a
+    /// standalone Merkle proof. The walk is driven through a
+    /// [`RecordingDB`](crate::RecordingDB) wrapping `db`, so any populated store
+    /// becomes a proof source.
+    ///
+    /// Provided on top of [`try_get`](Self::try_get), which is generic over the
+    /// backing store; implementors get the recorder for free and need not
+    /// change.
+    fn get_recorded(
+        &self,
+        db: &Self::DB,
+        key: &[u8],
+    ) -> Result<
+        (
+            Option<Vec<u8>>,
+            Vec<crate::Record<<Self::DB as NodeDB>::Node>>,
+        ),
+        Error,
+    > {
+        let mut recorder = crate::RecordingDB::new(db);
+        let value = self.try_get(&mut recorder, key)?;
+        Ok((value, recorder.drain()))
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -69,10 +120,10 @@ pub enum TrieUpdate {
 pub trait NodeDB {
     type Node;
     fn fork(&self) -> Self;
-    fn get(&self, index: &SH256) -> Option<Arc<Self::Node>>;
+    fn get(&self, index: &SH256) -> Result<Option<Arc<Self::Node>>, Error>;
     fn add_node(&mut self, node: &Arc<Self::Node>);
 
-    fn get_code(&mut self, hash: &SH256) -> Option<Arc<HexBytes>>;
+    fn get_code(&mut self, hash: &SH256) -> Result<Option<Arc<HexBytes>>, Error>;
     fn set_code(&mut self, hash: SH256, code: Cow<HexBytes>);
 
     fn remove_staging_node(&mut self, node: &Arc<Self::Node>);
@@ -80,6 +131,13 @@ pub trait NodeDB {
     fn commit(&mut self) -> usize;
 }
 
+/// Approximate in-memory footprint of a cached value, used by byte-bounded
+/// caches to budget memory rather than entry count.
+pub trait NodeWeight {
+    /// Returns the value's approximate size in bytes (e.g. its RLP length).
+    fn byte_len(&self) -> usize;
+}
+
 pub trait ProofFetcher {
     fn fetch_proofs(&self, key: &[u8]) -> Result<Vec<HexBytes>, String>;
     fn get_nodes(&self, node: &[SH256]) -> Result<Vec<HexBytes>, String>;